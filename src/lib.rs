@@ -1,19 +1,88 @@
+use std::collections::HashMap;
 use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
-use abi_stable::std_types::{RSlice, RSliceMut, RVec};
+use abi_stable::std_types::{RHashMap, RSlice, RSliceMut, RVec};
 use bytes::BytesMut;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod};
+use native_tls::TlsConnector;
 use postgres::{
-    types::{FromSql, ToSql},
-    Client, GenericClient, NoTls, Statement,
+    error::SqlState,
+    row::Row,
+    types::{FromSql, Kind, ToSql},
+    Client, GenericClient, NoTls, Statement, Transaction,
 };
+use postgres_native_tls::MakeTlsConnector;
 use postgres_types::Type;
+use rust_decimal::Decimal;
 use steel::{
     rvals::Custom,
     steel_vm::ffi::{FFIArg, FFIModule, FFIValue, RegisterFFIFn},
 };
+use uuid::Uuid;
 
 struct PostgresClient {
-    client: Client,
+    // `None` while a `PostgresCursor`/`PostgresTransaction` has checked the
+    // client out (see `cursor_open`/`txn_begin`), so it's impossible to issue
+    // a query through `self` that would alias the one the cursor/transaction
+    // is using, and impossible for the client to be dropped out from under
+    // them.
+    client: Option<Client>,
+    statements: HashMap<String, Statement>,
+}
+
+/// The libpq `sslmode` values we care about when deciding whether to
+/// negotiate TLS. Anything we don't recognize is treated as `Prefer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SslMode {
+    Disable,
+    Prefer,
+    Require,
+}
+
+/// Pull `sslmode=...` out of a libpq-style connection string (either the
+/// `key=value` or `postgres://...?key=value` form) without pulling in a
+/// full URI parser.
+fn parse_sslmode(params: &str) -> SslMode {
+    let needle = "sslmode=";
+
+    let Some(idx) = params.find(needle) else {
+        return SslMode::Prefer;
+    };
+
+    let value: String = params[idx + needle.len()..]
+        .chars()
+        .take_while(|c| !matches!(c, ' ' | '&' | ';'))
+        .collect();
+
+    match value.as_str() {
+        "disable" => SslMode::Disable,
+        "require" => SslMode::Require,
+        _ => SslMode::Prefer,
+    }
+}
+
+fn build_tls_connector(
+    root_cert_path: Option<&str>,
+    accept_invalid_certs: bool,
+) -> Result<MakeTlsConnector, PostgresError> {
+    let mut builder = TlsConnector::builder();
+
+    if accept_invalid_certs {
+        builder.danger_accept_invalid_certs(true);
+    }
+
+    if let Some(path) = root_cert_path {
+        let pem = std::fs::read(path).map_err(PostgresError::Io)?;
+        let cert = native_tls::Certificate::from_pem(&pem).map_err(PostgresError::Tls)?;
+        builder.add_root_certificate(cert);
+    }
+
+    let connector = builder.build().map_err(PostgresError::Tls)?;
+
+    Ok(MakeTlsConnector::new(connector))
 }
 
 unsafe impl Send for PostgresClient {}
@@ -21,14 +90,120 @@ unsafe impl Sync for PostgresClient {}
 
 enum Argument {
     Bool(bool),
-    Number(f64),
+    Number(DynamicToSqlNumber),
     Int(i32),
-    String(String),
+    BigInt(i64),
+    String(DynamicToSqlString),
     Void,
 }
 
 type ToSqlSync = dyn ToSql + Sync;
 
+/// Wraps a Steel string argument and serializes it according to whatever
+/// column type postgres asks for, so a single Steel string can bind against
+/// `TEXT`/`VARCHAR` as well as `UUID`, `NUMERIC`, `JSON`/`JSONB`, and the
+/// date/time types, none of which Steel has a native representation for.
+struct DynamicToSqlString(String);
+
+impl std::fmt::Debug for DynamicToSqlString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DynamicToSqlString({:?})", self.0)
+    }
+}
+
+impl ToSql for DynamicToSqlString {
+    fn to_sql(
+        &self,
+        ty: &postgres_types::Type,
+        out: &mut BytesMut,
+    ) -> Result<postgres_types::IsNull, Box<dyn Error + Sync + Send>> {
+        match *ty {
+            Type::UUID => self.0.parse::<Uuid>()?.to_sql(ty, out),
+            Type::JSON | Type::JSONB => {
+                serde_json::from_str::<serde_json::Value>(&self.0)?.to_sql(ty, out)
+            }
+            Type::NUMERIC => self.0.parse::<Decimal>()?.to_sql(ty, out),
+            Type::DATE => NaiveDate::parse_from_str(&self.0, "%Y-%m-%d")?.to_sql(ty, out),
+            Type::TIME => NaiveTime::parse_from_str(&self.0, "%H:%M:%S%.f")?.to_sql(ty, out),
+            Type::TIMESTAMP => {
+                NaiveDateTime::parse_from_str(&self.0, "%Y-%m-%dT%H:%M:%S%.f")?.to_sql(ty, out)
+            }
+            Type::TIMESTAMPTZ => DateTime::parse_from_rfc3339(&self.0)?
+                .with_timezone(&Utc)
+                .to_sql(ty, out),
+            _ => self.0.to_sql(ty, out),
+        }
+    }
+
+    fn accepts(ty: &postgres_types::Type) -> bool
+    where
+        Self: Sized,
+    {
+        matches!(
+            *ty,
+            Type::TEXT
+                | Type::VARCHAR
+                | Type::NAME
+                | Type::UUID
+                | Type::JSON
+                | Type::JSONB
+                | Type::NUMERIC
+                | Type::DATE
+                | Type::TIME
+                | Type::TIMESTAMP
+                | Type::TIMESTAMPTZ
+        )
+    }
+
+    fn to_sql_checked(
+        &self,
+        ty: &postgres_types::Type,
+        out: &mut BytesMut,
+    ) -> Result<postgres_types::IsNull, Box<dyn Error + Sync + Send>> {
+        self.to_sql(ty, out)
+    }
+}
+
+/// Wraps a Steel number argument (Steel only has one numeric `FFIArg`
+/// variant, `NumV(f64)`) and serializes it as whatever precision postgres
+/// asks for, so the same Steel number can bind against `FLOAT4` as well as
+/// `FLOAT8`.
+struct DynamicToSqlNumber(f64);
+
+impl std::fmt::Debug for DynamicToSqlNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DynamicToSqlNumber({:?})", self.0)
+    }
+}
+
+impl ToSql for DynamicToSqlNumber {
+    fn to_sql(
+        &self,
+        ty: &postgres_types::Type,
+        out: &mut BytesMut,
+    ) -> Result<postgres_types::IsNull, Box<dyn Error + Sync + Send>> {
+        match *ty {
+            Type::FLOAT4 => (self.0 as f32).to_sql(ty, out),
+            _ => self.0.to_sql(ty, out),
+        }
+    }
+
+    fn accepts(ty: &postgres_types::Type) -> bool
+    where
+        Self: Sized,
+    {
+        matches!(*ty, Type::FLOAT4 | Type::FLOAT8)
+    }
+
+    fn to_sql_checked(
+        &self,
+        ty: &postgres_types::Type,
+        out: &mut BytesMut,
+    ) -> Result<postgres_types::IsNull, Box<dyn Error + Sync + Send>> {
+        self.to_sql(ty, out)
+    }
+}
+
 struct DynamicToSqlNoneType;
 
 impl std::fmt::Debug for DynamicToSqlNoneType {
@@ -68,135 +243,805 @@ impl ToSql for DynamicToSqlNoneType {
 impl PostgresClient {
     pub fn connect(params: String) -> Self {
         Self {
-            client: Client::connect(&params, NoTls).unwrap(),
+            client: Some(Client::connect(&params, NoTls).unwrap()),
+            statements: HashMap::new(),
         }
     }
 
+    /// Like `connect`, but negotiates TLS via `native-tls` when the
+    /// connection string's `sslmode` calls for it (`prefer`/`require`).
+    /// `root_cert_path` can be used to pin a CA, and `accept_invalid_certs`
+    /// bypasses validation entirely for talking to instances with
+    /// self-signed certificates.
+    pub fn connect_tls(
+        params: String,
+        root_cert_path: Option<String>,
+        accept_invalid_certs: bool,
+    ) -> Result<Self, PostgresError> {
+        let client = match parse_sslmode(&params) {
+            SslMode::Disable => Client::connect(&params, NoTls)?,
+            SslMode::Require => {
+                let connector = build_tls_connector(root_cert_path.as_deref(), accept_invalid_certs)?;
+                Client::connect(&params, connector)?
+            }
+            // libpq's `prefer` means "use TLS if the server will do it,
+            // otherwise fall back to plaintext" -- unlike `require`, a
+            // failed TLS handshake isn't fatal here.
+            SslMode::Prefer => {
+                let connector = build_tls_connector(root_cert_path.as_deref(), accept_invalid_certs)?;
+                match Client::connect(&params, connector) {
+                    Ok(client) => client,
+                    Err(_) => Client::connect(&params, NoTls)?,
+                }
+            }
+        };
+
+        Ok(Self {
+            client: Some(client),
+            statements: HashMap::new(),
+        })
+    }
+
+    /// Borrow the underlying connection, failing if it's currently checked
+    /// out by an open `PostgresCursor`/`PostgresTransaction`.
+    fn client_mut(&mut self) -> Result<&mut Client, PostgresError> {
+        self.client.as_mut().ok_or(PostgresError::ClientCheckedOut)
+    }
+
     pub fn batch_execute(&mut self, queries: &str) -> Result<(), PostgresError> {
-        Ok(self.client.batch_execute(queries)?)
+        Ok(self.client_mut()?.batch_execute(queries)?)
     }
 
     pub fn execute(&mut self, query: &str, bindings: FFIArg) -> Result<FFIValue, PostgresError> {
-        if let FFIArg::Vector(bindings) = bindings {
-            // Why does this not satisfy the borrow checker?
-            let converted: Vec<Argument> = bindings
-                .iter()
-                .map(|arg| match arg {
-                    FFIArg::BoolV(b) => Argument::Bool(*b),
-                    FFIArg::NumV(n) => Argument::Number(*n),
-                    FFIArg::IntV(i) => Argument::Int(*i as _),
-                    FFIArg::StringRef(s) => Argument::String(s.to_string()),
-                    FFIArg::StringV(s) => Argument::String(s.to_string()),
-                    FFIArg::Void => Argument::Void,
-                    a => todo!("{:?}", a),
-                })
-                .collect();
-
-            let references: Vec<&ToSqlSync> = converted
-                .iter()
-                .map(|arg| -> &ToSqlSync {
-                    match arg {
-                        Argument::Bool(b) => b,
-                        Argument::Number(n) => n,
-                        Argument::Int(i) => i,
-                        Argument::String(s) => s,
-                        Argument::Void => &DynamicToSqlNoneType,
-                    }
-                })
-                .collect();
-
-            Ok(self
-                .client
-                .execute(query, references.as_slice())
-                .map(|x| FFIValue::IntV(x as _))?)
-        } else {
-            Err(PostgresError::TypeMismatch)
-        }
+        execute_generic(self.client_mut()?, query, bindings)
     }
 
     // Return a raw row, which then will get converted based on
     // type markers?
-    pub fn query(&mut self, query: &str) -> Result<FFIValue, PostgresError> {
-        let rows = self.client.query(query, &[])?;
+    pub fn query(&mut self, query: &str, bindings: FFIArg) -> Result<FFIValue, PostgresError> {
+        query_generic(self.client_mut()?, query, bindings)
+    }
+
+    /// Begin a transaction. Statements run through `PostgresTransaction`'s
+    /// `execute`/`query` are only visible once `txn/commit` is called, and
+    /// are discarded if `txn/rollback` is called (or the transaction is
+    /// dropped without either).
+    ///
+    /// The underlying connection is moved into the returned
+    /// `PostgresTransaction` (see `PostgresTransaction::open`), so `self` has
+    /// no client left to alias or outlive until `txn/commit`/`txn/rollback`
+    /// hands it back.
+    pub fn txn_begin(&mut self) -> Result<PostgresTransaction, PostgresError> {
+        let client = self.client.take().ok_or(PostgresError::ClientCheckedOut)?;
+
+        PostgresTransaction::open(client).map_err(|(client, err)| {
+            // A failed `BEGIN` shouldn't permanently brick `self` -- give the
+            // checked-out client back so the next call can use it.
+            self.client = Some(client);
+            err
+        })
+    }
+
+    /// Open a server-side cursor for `query` inside its own transaction, so
+    /// `PostgresCursor::next_batch` can stream results in chunks of at most
+    /// `row_limit` rows instead of materializing the whole result set.
+    ///
+    /// The underlying connection is moved into the returned `PostgresCursor`
+    /// (see `PostgresCursor::open`), so `self` has no client left to alias
+    /// or outlive until `cursor/close` hands it back.
+    pub fn cursor_open(
+        &mut self,
+        query: String,
+        bindings: FFIArg,
+        row_limit: i64,
+    ) -> Result<PostgresCursor, PostgresError> {
+        let client = self.client.take().ok_or(PostgresError::ClientCheckedOut)?;
+
+        PostgresCursor::open(client, &query, bindings, row_limit).map_err(|(client, err)| {
+            // A bad query/binding shouldn't permanently brick `self` -- give
+            // the checked-out client back so the next call can use it.
+            self.client = Some(client);
+            err
+        })
+    }
+
+    /// Like `query`, but returns each row as a Steel hash-map keyed by
+    /// column name instead of a positional vector.
+    pub fn query_assoc(&mut self, query: &str, bindings: FFIArg) -> Result<FFIValue, PostgresError> {
+        let converted = convert_bindings(bindings)?;
+        let references = to_sql_refs(&converted);
+
+        let rows = self.client_mut()?.query(query, references.as_slice())?;
 
         let mut results = RVec::new();
 
         for row in rows {
-            let width = row.len();
-
-            let mut computed_row: RVec<FFIValue> = RVec::with_capacity(width);
-
-            for i in (0..width).into_iter() {
-                // Type check the row coming in
-                let typ = row.columns()[i].type_().clone();
-
-                match typ {
-                    typ if typ == Type::BOOL => {
-                        let value = row
-                            .get::<_, Option<bool>>(i)
-                            .map(|x| FFIValue::BoolV(x.into()))
-                            .unwrap_or(FFIValue::Void);
-
-                        computed_row.push(value);
-                    }
-                    typ if typ == Type::TEXT => {
-                        // TODO
-                        let value = row
-                            .get::<_, Option<String>>(i)
-                            .map(|x| FFIValue::StringV(x.into()))
-                            .unwrap_or(FFIValue::Void);
-
-                        computed_row.push(value);
-                    }
-                    typ if typ == Type::BYTEA => {
-                        let value = row
-                            .get::<_, Option<Vec<u8>>>(i)
-                            .map(|x| FFIValue::ByteVector(x.into()))
-                            .unwrap_or(FFIValue::Void);
-
-                        computed_row.push(value);
-                    }
-
-                    typ if typ == Type::INT2 => {
-                        let value = row
-                            .get::<_, Option<i16>>(i)
-                            .map(|x| FFIValue::IntV(x as _))
-                            .unwrap_or(FFIValue::Void);
-
-                        computed_row.push(value);
-                    }
-
-                    typ if typ == Type::INT4 => {
-                        let value = row
-                            .get::<_, Option<i32>>(i)
-                            .map(|x| FFIValue::IntV(x as _))
-                            .unwrap_or(FFIValue::Void);
-
-                        computed_row.push(value);
-                    }
-
-                    typ if typ == Type::INT8 => {
-                        let value = row
-                            .get::<_, Option<i64>>(i)
-                            .map(|x| FFIValue::IntV(x as _))
-                            .unwrap_or(FFIValue::Void);
-
-                        computed_row.push(value);
-                    }
-
-                    _ => {
-                        todo!()
-                    }
-                }
+            results.push(decode_row_assoc(&row)?);
+        }
+
+        Ok(FFIValue::Vector(results))
+    }
+
+    /// Prepare `sql` once and cache it keyed by the SQL text, so repeated
+    /// `execute_prepared`/`query_prepared` calls skip the parse/plan
+    /// round-trip. Returns a handle that can be passed to either.
+    pub fn prepare(&mut self, sql: String) -> Result<PostgresStatement, PostgresError> {
+        if let Some(statement) = self.statements.get(&sql) {
+            return Ok(PostgresStatement(statement.clone()));
+        }
+
+        let statement = self.client_mut()?.prepare(&sql)?;
+        self.statements.insert(sql, statement.clone());
+
+        Ok(PostgresStatement(statement))
+    }
+
+    pub fn execute_prepared(
+        &mut self,
+        statement: &PostgresStatement,
+        bindings: FFIArg,
+    ) -> Result<FFIValue, PostgresError> {
+        let converted = convert_bindings(bindings)?;
+        let references = to_sql_refs(&converted);
+
+        Ok(self
+            .client_mut()?
+            .execute(&statement.0, references.as_slice())
+            .map(|x| FFIValue::IntV(x as _))?)
+    }
+
+    pub fn query_prepared(
+        &mut self,
+        statement: &PostgresStatement,
+        bindings: FFIArg,
+    ) -> Result<FFIValue, PostgresError> {
+        let converted = convert_bindings(bindings)?;
+        let references = to_sql_refs(&converted);
+
+        let rows = self
+            .client_mut()?
+            .query(&statement.0, references.as_slice())?;
+
+        let mut results = RVec::new();
+
+        for row in rows {
+            results.push(decode_row(&row)?);
+        }
+
+        Ok(FFIValue::Vector(results))
+    }
+}
+
+/// A cached prepared statement handle, returned by `PostgresClient::prepare`
+/// and consumed by `execute_prepared`/`query_prepared`.
+struct PostgresStatement(Statement);
+
+impl Custom for PostgresStatement {}
+
+/// Decode every column of `row` into a Steel hash-map keyed by column name.
+fn decode_row_assoc(row: &Row) -> Result<FFIValue, PostgresError> {
+    let pairs = row
+        .columns()
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            let typ = column.type_().clone();
+            decode_field(row, i, &typ)
+                .map(|value| (FFIValue::StringV(column.name().to_string().into()), value))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(FFIValue::HashMap(pairs.into_iter().collect()))
+}
+
+/// Decode every column of `row` into a Steel `Vector`, in column order.
+fn decode_row(row: &Row) -> Result<FFIValue, PostgresError> {
+    let width = row.len();
+    let mut computed_row: RVec<FFIValue> = RVec::with_capacity(width);
+
+    for i in 0..width {
+        let typ = row.columns()[i].type_().clone();
+        computed_row.push(decode_field(row, i, &typ)?);
+    }
+
+    Ok(FFIValue::Vector(computed_row))
+}
+
+static CURSOR_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A server-side cursor opened by `PostgresClient::cursor_open`. Rows are
+/// fetched in batches of `row_limit` (or all at once when `row_limit <= 0`)
+/// so a script can stream a huge result set without holding it all in
+/// memory at once.
+struct PostgresCursor {
+    // `transaction` borrows `client` through an unsafe `'static` cast (see
+    // `open`), so it must be dropped first -- Rust drops struct fields in
+    // declaration order, and `transaction`'s own `Drop` impl issues a
+    // rollback over the connection, which would be a use-after-free if
+    // `client` were freed first. This ordering covers the implicit drop path
+    // (a script that never calls `close`), not just the explicit one: `close`
+    // also takes `transaction` before `client` regardless of field order, but
+    // the field order is what protects the case where `close` never runs.
+    transaction: Option<Transaction<'static>>,
+    // Boxed so its address is stable once `open` ties `transaction`'s
+    // `'static` lifetime to it: the box's contents never move even if this
+    // struct itself does.
+    client: Option<Box<Client>>,
+    name: String,
+    row_limit: i64,
+    exhausted: bool,
+}
+
+unsafe impl Send for PostgresCursor {}
+unsafe impl Sync for PostgresCursor {}
+
+impl PostgresCursor {
+    /// Check out `client` to back a new cursor. On success the connection is
+    /// owned by the returned `PostgresCursor` until `close` hands it back. On
+    /// failure `client` is handed back in the error so the caller (see
+    /// `PostgresClient::cursor_open`) can restore it instead of losing it.
+    fn open(
+        client: Client,
+        query: &str,
+        bindings: FFIArg,
+        row_limit: i64,
+    ) -> Result<Self, (Client, PostgresError)> {
+        let converted = match convert_bindings(bindings) {
+            Ok(converted) => converted,
+            Err(err) => return Err((client, err)),
+        };
+        let references = to_sql_refs(&converted);
+
+        let mut boxed = Box::new(client);
+        let client_ref: &'static mut Client = unsafe { &mut *(boxed.as_mut() as *mut Client) };
+
+        let mut transaction = match client_ref.transaction() {
+            Ok(transaction) => transaction,
+            Err(err) => return Err((*boxed, err.into())),
+        };
+
+        let id = CURSOR_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let name = format!("steel_postgres_cursor_{id}");
+
+        if let Err(err) = transaction.execute(
+            format!("DECLARE {name} CURSOR FOR {query}").as_str(),
+            references.as_slice(),
+        ) {
+            drop(transaction);
+            return Err((*boxed, err.into()));
+        }
+
+        Ok(PostgresCursor {
+            transaction: Some(transaction),
+            client: Some(boxed),
+            name,
+            row_limit,
+            exhausted: false,
+        })
+    }
+
+    pub fn next_batch(&mut self) -> Result<FFIValue, PostgresError> {
+        if self.exhausted {
+            return Ok(FFIValue::Vector(RVec::new()));
+        }
+
+        let Some(transaction) = self.transaction.as_ref() else {
+            return Err(PostgresError::CursorClosed);
+        };
+
+        let fetch = if self.row_limit <= 0 {
+            format!("FETCH ALL FROM {}", self.name)
+        } else {
+            format!("FETCH FORWARD {} FROM {}", self.row_limit, self.name)
+        };
+
+        let rows = transaction.query(fetch.as_str(), &[])?;
+
+        if self.row_limit <= 0 || rows.len() < self.row_limit.max(1) as usize {
+            self.exhausted = true;
+        }
+
+        let mut results = RVec::new();
+
+        for row in rows {
+            results.push(decode_row(&row)?);
+        }
+
+        Ok(FFIValue::Vector(results))
+    }
+
+    /// Commit the cursor's transaction and return the connection to
+    /// `client`, so it's usable again.
+    pub fn close(&mut self, client: &mut PostgresClient) -> Result<(), PostgresError> {
+        if let Some(transaction) = self.transaction.take() {
+            transaction.commit()?;
+        }
+
+        if let Some(boxed) = self.client.take() {
+            client.client = Some(*boxed);
+        }
+
+        Ok(())
+    }
+}
+
+impl Custom for PostgresCursor {}
+
+/// A transaction opened by `PostgresClient::txn_begin`. `execute`/`query` go
+/// through the same `execute_generic`/`query_generic` helpers as
+/// `PostgresClient`, since `postgres::Transaction` implements `GenericClient`
+/// the same as `Client` does.
+struct PostgresTransaction {
+    // Same reasoning as `PostgresCursor`: `transaction` borrows `client`
+    // through an unsafe `'static` cast, so it must be declared (and thus
+    // dropped) first, or a script that never calls `commit`/`rollback` would
+    // free `client` before `transaction`'s own rollback-on-drop runs against
+    // it.
+    transaction: Option<Transaction<'static>>,
+    // Boxed for a stable address, and only ever moved out once `transaction`
+    // has been consumed by `commit`/`rollback`.
+    client: Option<Box<Client>>,
+}
+
+unsafe impl Send for PostgresTransaction {}
+unsafe impl Sync for PostgresTransaction {}
+
+impl PostgresTransaction {
+    /// Check out `client` to back a new transaction. On success the
+    /// connection is owned by the returned `PostgresTransaction` until
+    /// `commit`/`rollback` hands it back. On failure `client` is handed back
+    /// in the error so the caller (see `PostgresClient::txn_begin`) can
+    /// restore it instead of losing it.
+    fn open(client: Client) -> Result<Self, (Client, PostgresError)> {
+        let mut boxed = Box::new(client);
+        let client_ref: &'static mut Client = unsafe { &mut *(boxed.as_mut() as *mut Client) };
+
+        let transaction = match client_ref.transaction() {
+            Ok(transaction) => transaction,
+            Err(err) => return Err((*boxed, err.into())),
+        };
+
+        Ok(PostgresTransaction {
+            transaction: Some(transaction),
+            client: Some(boxed),
+        })
+    }
+
+    pub fn execute(&mut self, query: &str, bindings: FFIArg) -> Result<FFIValue, PostgresError> {
+        let transaction = self
+            .transaction
+            .as_mut()
+            .ok_or(PostgresError::TransactionClosed)?;
+
+        execute_generic(transaction, query, bindings)
+    }
+
+    pub fn query(&mut self, query: &str, bindings: FFIArg) -> Result<FFIValue, PostgresError> {
+        let transaction = self
+            .transaction
+            .as_mut()
+            .ok_or(PostgresError::TransactionClosed)?;
+
+        query_generic(transaction, query, bindings)
+    }
+
+    /// Commit the transaction and return the connection to `out`, so it's
+    /// usable again.
+    pub fn commit(&mut self, out: &mut PostgresClient) -> Result<(), PostgresError> {
+        match self.transaction.take() {
+            Some(transaction) => {
+                transaction.commit()?;
+                self.give_back(out);
+                Ok(())
             }
+            None => Err(PostgresError::TransactionClosed),
+        }
+    }
 
-            results.push(FFIValue::Vector(computed_row));
+    /// Roll back the transaction and return the connection to `out`, so
+    /// it's usable again.
+    pub fn rollback(&mut self, out: &mut PostgresClient) -> Result<(), PostgresError> {
+        match self.transaction.take() {
+            Some(transaction) => {
+                transaction.rollback()?;
+                self.give_back(out);
+                Ok(())
+            }
+            None => Err(PostgresError::TransactionClosed),
+        }
+    }
+
+    fn give_back(&mut self, out: &mut PostgresClient) {
+        if let Some(boxed) = self.client.take() {
+            out.client = Some(*boxed);
+        }
+    }
+}
+
+impl Custom for PostgresTransaction {}
+
+/// An opt-in connection pool, following cornucopia's deadpool integration.
+/// Since `deadpool_postgres` is async, `pool/get` blocks the calling Steel
+/// thread on a dedicated Tokio runtime rather than exposing async semantics
+/// across the FFI boundary.
+struct PostgresPool {
+    pool: Pool,
+    runtime: Arc<tokio::runtime::Runtime>,
+}
+
+unsafe impl Send for PostgresPool {}
+unsafe impl Sync for PostgresPool {}
+
+impl PostgresPool {
+    /// Like `PostgresClient::connect_tls`, but for a pooled connection:
+    /// `sslmode` in `params` decides whether pooled connections negotiate
+    /// TLS.
+    ///
+    /// Unlike `connect_tls`, there's no plaintext fallback for `prefer` here:
+    /// each checkout reconnects lazily through `deadpool`'s own manager, so
+    /// there's no single connect attempt to fall back from the way there is
+    /// in `connect_tls`. Rather than have unset `sslmode` (which defaults to
+    /// `prefer`) require a TLS handshake on every checkout -- a behavior
+    /// change from before pooled TLS support existed, and a foot-gun against
+    /// any plain local Postgres -- `prefer` is treated the same as `disable`
+    /// here. Pass `sslmode=require` explicitly to get a pool that negotiates
+    /// TLS.
+    pub fn connect(
+        params: String,
+        max_size: usize,
+        root_cert_path: Option<String>,
+        accept_invalid_certs: bool,
+    ) -> Result<Self, PostgresError> {
+        let runtime = tokio::runtime::Runtime::new().map_err(PostgresError::Io)?;
+
+        let config = params.parse::<tokio_postgres::Config>()?;
+        let manager_config = ManagerConfig {
+            recycling_method: RecyclingMethod::Fast,
+        };
+
+        let pool = match parse_sslmode(&params) {
+            SslMode::Disable | SslMode::Prefer => {
+                let manager = Manager::from_config(config, tokio_postgres::NoTls, manager_config);
+                Pool::builder(manager).max_size(max_size).build()
+            }
+            SslMode::Require => {
+                let connector = build_tls_connector(root_cert_path.as_deref(), accept_invalid_certs)?;
+                let manager = Manager::from_config(config, connector, manager_config);
+                Pool::builder(manager).max_size(max_size).build()
+            }
+        }
+        .map_err(|err| PostgresError::PoolConfig(err.to_string()))?;
+
+        Ok(Self {
+            pool,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// Check out a client, so concurrent Steel workers don't serialize on a
+    /// single connection.
+    pub fn get(&mut self) -> Result<PostgresPooledClient, PostgresError> {
+        let client = self.runtime.block_on(self.pool.get())?;
+
+        Ok(PostgresPooledClient {
+            client,
+            runtime: self.runtime.clone(),
+        })
+    }
+}
+
+impl Custom for PostgresPool {}
+
+/// A client checked out of a `PostgresPool`. Released back to the pool when
+/// dropped.
+struct PostgresPooledClient {
+    client: deadpool_postgres::Client,
+    runtime: Arc<tokio::runtime::Runtime>,
+}
+
+unsafe impl Send for PostgresPooledClient {}
+unsafe impl Sync for PostgresPooledClient {}
+
+impl PostgresPooledClient {
+    pub fn execute(&mut self, query: &str, bindings: FFIArg) -> Result<FFIValue, PostgresError> {
+        let converted = convert_bindings(bindings)?;
+        let references = to_sql_refs(&converted);
+
+        let count = self
+            .runtime
+            .block_on(self.client.execute(query, references.as_slice()))?;
+
+        Ok(FFIValue::IntV(count as _))
+    }
+
+    pub fn query(&mut self, query: &str, bindings: FFIArg) -> Result<FFIValue, PostgresError> {
+        let converted = convert_bindings(bindings)?;
+        let references = to_sql_refs(&converted);
+
+        let rows = self
+            .runtime
+            .block_on(self.client.query(query, references.as_slice()))?;
+
+        let mut results = RVec::new();
+
+        for row in rows {
+            results.push(decode_row(&row)?);
         }
 
         Ok(FFIValue::Vector(results))
     }
 }
 
+impl Custom for PostgresPooledClient {}
+
+/// Convert the Steel-side `(list ...)` of bindings passed to `execute`/`query`
+/// into owned `Argument`s, shared by both so their binding behavior never
+/// drifts apart.
+fn convert_bindings(bindings: FFIArg) -> Result<Vec<Argument>, PostgresError> {
+    let FFIArg::Vector(bindings) = bindings else {
+        return Err(PostgresError::TypeMismatch);
+    };
+
+    bindings
+        .iter()
+        .map(|arg| match arg {
+            FFIArg::BoolV(b) => Ok(Argument::Bool(*b)),
+            FFIArg::NumV(n) => Ok(Argument::Number(DynamicToSqlNumber(*n))),
+            FFIArg::IntV(i) => Ok(match i32::try_from(*i as i64) {
+                Ok(small) => Argument::Int(small),
+                Err(_) => Argument::BigInt(*i as i64),
+            }),
+            FFIArg::StringRef(s) => Ok(Argument::String(DynamicToSqlString(s.to_string()))),
+            FFIArg::StringV(s) => Ok(Argument::String(DynamicToSqlString(s.to_string()))),
+            FFIArg::Void => Ok(Argument::Void),
+            // Anything else (lists, closures, opaque custom types, ...)
+            // can't be bound as a SQL parameter -- report it rather than
+            // crashing the host on a stray argument type.
+            a => Err(PostgresError::UnsupportedType(format!("{:?}", a))),
+        })
+        .collect()
+}
+
+/// Borrow each converted `Argument` as a `&dyn ToSql` in the form
+/// `Client::execute`/`Client::query` expect.
+fn to_sql_refs(args: &[Argument]) -> Vec<&ToSqlSync> {
+    args.iter()
+        .map(|arg| -> &ToSqlSync {
+            match arg {
+                Argument::Bool(b) => b,
+                Argument::Number(n) => n,
+                Argument::Int(i) => i,
+                Argument::BigInt(i) => i,
+                Argument::String(s) => s,
+                Argument::Void => &DynamicToSqlNoneType,
+            }
+        })
+        .collect()
+}
+
+/// Shared by `PostgresClient::execute` and `PostgresTransaction::execute` via
+/// `GenericClient`, so a plain `Client` and a `Transaction` run statements
+/// identically.
+fn execute_generic<C: GenericClient>(
+    client: &mut C,
+    query: &str,
+    bindings: FFIArg,
+) -> Result<FFIValue, PostgresError> {
+    let converted = convert_bindings(bindings)?;
+    let references = to_sql_refs(&converted);
+
+    Ok(client
+        .execute(query, references.as_slice())
+        .map(|x| FFIValue::IntV(x as _))?)
+}
+
+/// The `query` counterpart to `execute_generic`.
+fn query_generic<C: GenericClient>(
+    client: &mut C,
+    query: &str,
+    bindings: FFIArg,
+) -> Result<FFIValue, PostgresError> {
+    let converted = convert_bindings(bindings)?;
+    let references = to_sql_refs(&converted);
+
+    let rows = client.query(query, references.as_slice())?;
+
+    let mut results = RVec::new();
+
+    for row in rows {
+        results.push(decode_row(&row)?);
+    }
+
+    Ok(FFIValue::Vector(results))
+}
+
+/// Decode a single column out of `row`, dispatching on its runtime postgres
+/// `Type` to the matching `FromSql` implementation. Array columns are
+/// detected via `Type::kind()` and decoded recursively through
+/// `decode_array_field`.
+fn decode_field(row: &Row, i: usize, typ: &Type) -> Result<FFIValue, PostgresError> {
+    if let Kind::Array(inner) = typ.kind() {
+        return decode_array_field(row, i, inner);
+    }
+
+    Ok(match typ {
+        typ if *typ == Type::BOOL => row
+            .get::<_, Option<bool>>(i)
+            .map(FFIValue::BoolV)
+            .unwrap_or(FFIValue::Void),
+
+        typ if *typ == Type::TEXT || *typ == Type::VARCHAR || *typ == Type::NAME => row
+            .get::<_, Option<String>>(i)
+            .map(text_to_ffi)
+            .unwrap_or(FFIValue::Void),
+
+        typ if *typ == Type::BYTEA => row
+            .get::<_, Option<Vec<u8>>>(i)
+            .map(bytea_to_ffi)
+            .unwrap_or(FFIValue::Void),
+
+        typ if *typ == Type::INT2 => row
+            .get::<_, Option<i16>>(i)
+            .map(|x| FFIValue::IntV(x as _))
+            .unwrap_or(FFIValue::Void),
+
+        typ if *typ == Type::INT4 => row
+            .get::<_, Option<i32>>(i)
+            .map(|x| FFIValue::IntV(x as _))
+            .unwrap_or(FFIValue::Void),
+
+        typ if *typ == Type::INT8 => row
+            .get::<_, Option<i64>>(i)
+            .map(|x| FFIValue::IntV(x as _))
+            .unwrap_or(FFIValue::Void),
+
+        typ if *typ == Type::FLOAT4 => row
+            .get::<_, Option<f32>>(i)
+            .map(|x| FFIValue::NumV(x as _))
+            .unwrap_or(FFIValue::Void),
+
+        typ if *typ == Type::FLOAT8 => row
+            .get::<_, Option<f64>>(i)
+            .map(FFIValue::NumV)
+            .unwrap_or(FFIValue::Void),
+
+        // Decimal doesn't map cleanly onto a Steel number, so surface it as
+        // a string and let the caller parse it if it needs to do math.
+        typ if *typ == Type::NUMERIC => row
+            .get::<_, Option<Decimal>>(i)
+            .map(numeric_to_ffi)
+            .unwrap_or(FFIValue::Void),
+
+        typ if *typ == Type::UUID => row
+            .get::<_, Option<Uuid>>(i)
+            .map(uuid_to_ffi)
+            .unwrap_or(FFIValue::Void),
+
+        typ if *typ == Type::DATE => row
+            .get::<_, Option<NaiveDate>>(i)
+            .map(date_to_ffi)
+            .unwrap_or(FFIValue::Void),
+
+        typ if *typ == Type::TIME => row
+            .get::<_, Option<NaiveTime>>(i)
+            .map(time_to_ffi)
+            .unwrap_or(FFIValue::Void),
+
+        typ if *typ == Type::TIMESTAMP => row
+            .get::<_, Option<NaiveDateTime>>(i)
+            .map(timestamp_to_ffi)
+            .unwrap_or(FFIValue::Void),
+
+        typ if *typ == Type::TIMESTAMPTZ => row
+            .get::<_, Option<DateTime<Utc>>>(i)
+            .map(timestamptz_to_ffi)
+            .unwrap_or(FFIValue::Void),
+
+        typ if *typ == Type::JSON || *typ == Type::JSONB => row
+            .get::<_, Option<serde_json::Value>>(i)
+            .map(json_to_ffi)
+            .unwrap_or(FFIValue::Void),
+
+        typ => return Err(PostgresError::UnsupportedType(typ.name().to_string())),
+    })
+}
+
+fn text_to_ffi(x: String) -> FFIValue {
+    FFIValue::StringV(x.into())
+}
+
+fn bytea_to_ffi(x: Vec<u8>) -> FFIValue {
+    FFIValue::ByteVector(x.into())
+}
+
+fn numeric_to_ffi(x: Decimal) -> FFIValue {
+    FFIValue::StringV(x.to_string().into())
+}
+
+fn uuid_to_ffi(x: Uuid) -> FFIValue {
+    FFIValue::StringV(x.to_string().into())
+}
+
+fn date_to_ffi(x: NaiveDate) -> FFIValue {
+    FFIValue::StringV(x.to_string().into())
+}
+
+fn time_to_ffi(x: NaiveTime) -> FFIValue {
+    FFIValue::StringV(x.to_string().into())
+}
+
+fn timestamp_to_ffi(x: NaiveDateTime) -> FFIValue {
+    FFIValue::StringV(x.format("%Y-%m-%dT%H:%M:%S%.f").to_string().into())
+}
+
+fn timestamptz_to_ffi(x: DateTime<Utc>) -> FFIValue {
+    FFIValue::StringV(x.to_rfc3339().into())
+}
+
+/// Decode an array column whose element type is `inner`, via
+/// `row.get::<_, Vec<Option<T>>>` for whichever `T` matches `inner`. Covers
+/// the same set of types as `decode_field` (built on the same conversion
+/// functions) so array columns don't silently lag behind their scalar
+/// counterparts.
+fn decode_array_field(row: &Row, i: usize, inner: &Type) -> Result<FFIValue, PostgresError> {
+    macro_rules! array_of {
+        ($ty:ty, $to_value:expr) => {{
+            row.get::<_, Option<Vec<Option<$ty>>>>(i)
+                .map(|elements| {
+                    FFIValue::Vector(
+                        elements
+                            .into_iter()
+                            .map(|x| x.map($to_value).unwrap_or(FFIValue::Void))
+                            .collect(),
+                    )
+                })
+                .unwrap_or(FFIValue::Void)
+        }};
+    }
+
+    Ok(match inner {
+        typ if *typ == Type::BOOL => array_of!(bool, FFIValue::BoolV),
+        typ if *typ == Type::TEXT || *typ == Type::VARCHAR || *typ == Type::NAME => {
+            array_of!(String, text_to_ffi)
+        }
+        typ if *typ == Type::BYTEA => array_of!(Vec<u8>, bytea_to_ffi),
+        typ if *typ == Type::INT2 => array_of!(i16, |x: i16| FFIValue::IntV(x as _)),
+        typ if *typ == Type::INT4 => array_of!(i32, |x: i32| FFIValue::IntV(x as _)),
+        typ if *typ == Type::INT8 => array_of!(i64, |x: i64| FFIValue::IntV(x as _)),
+        typ if *typ == Type::FLOAT4 => array_of!(f32, |x: f32| FFIValue::NumV(x as _)),
+        typ if *typ == Type::FLOAT8 => array_of!(f64, FFIValue::NumV),
+        typ if *typ == Type::NUMERIC => array_of!(Decimal, numeric_to_ffi),
+        typ if *typ == Type::UUID => array_of!(Uuid, uuid_to_ffi),
+        typ if *typ == Type::DATE => array_of!(NaiveDate, date_to_ffi),
+        typ if *typ == Type::TIME => array_of!(NaiveTime, time_to_ffi),
+        typ if *typ == Type::TIMESTAMP => array_of!(NaiveDateTime, timestamp_to_ffi),
+        typ if *typ == Type::TIMESTAMPTZ => array_of!(DateTime<Utc>, timestamptz_to_ffi),
+        typ if *typ == Type::JSON || *typ == Type::JSONB => {
+            array_of!(serde_json::Value, json_to_ffi)
+        }
+        typ => return Err(PostgresError::UnsupportedType(format!("{}[]", typ.name()))),
+    })
+}
+
+/// Map a decoded `JSON`/`JSONB` value into the Steel values a script would
+/// construct by hand: objects become hash-maps, arrays become vectors.
+fn json_to_ffi(value: serde_json::Value) -> FFIValue {
+    match value {
+        serde_json::Value::Null => FFIValue::Void,
+        serde_json::Value::Bool(b) => FFIValue::BoolV(b),
+        serde_json::Value::Number(n) => FFIValue::NumV(n.as_f64().unwrap_or_default()),
+        serde_json::Value::String(s) => FFIValue::StringV(s.into()),
+        serde_json::Value::Array(values) => {
+            FFIValue::Vector(values.into_iter().map(json_to_ffi).collect())
+        }
+        serde_json::Value::Object(map) => FFIValue::HashMap(
+            map.into_iter()
+                .map(|(k, v)| (FFIValue::StringV(k.into()), json_to_ffi(v)))
+                .collect(),
+        ),
+    }
+}
+
 impl Custom for PostgresClient {}
 
 #[allow(dead_code)]
@@ -204,10 +1049,54 @@ impl Custom for PostgresClient {}
 enum PostgresError {
     Error(postgres::Error),
     TypeMismatch,
+    Tls(native_tls::Error),
+    Io(std::io::Error),
+    UnsupportedType(String),
+    CursorClosed,
+    TransactionClosed,
+    ClientCheckedOut,
+    Pool(deadpool_postgres::PoolError),
+    PoolConfig(String),
 }
 
 impl Custom for PostgresError {}
 
+impl From<deadpool_postgres::PoolError> for PostgresError {
+    fn from(value: deadpool_postgres::PoolError) -> Self {
+        Self::Pool(value)
+    }
+}
+
+impl PostgresError {
+    /// The five-character SQLSTATE code (e.g. `"23505"`), when this error
+    /// came back from the server rather than from the driver itself.
+    pub fn sqlstate(&self) -> Option<String> {
+        match self {
+            PostgresError::Error(err) => err.code().map(|code| code.code().to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn is_unique_violation(&self) -> bool {
+        self.sqlstate().as_deref() == Some(SqlState::UNIQUE_VIOLATION.code())
+    }
+
+    pub fn is_foreign_key_violation(&self) -> bool {
+        self.sqlstate().as_deref() == Some(SqlState::FOREIGN_KEY_VIOLATION.code())
+    }
+
+    /// The server's primary error message, when this error came back from
+    /// the server rather than from the driver itself.
+    pub fn message(&self) -> Option<String> {
+        match self {
+            PostgresError::Error(err) => err
+                .as_db_error()
+                .map(|db_error| db_error.message().to_string()),
+            _ => None,
+        }
+    }
+}
+
 impl From<postgres::Error> for PostgresError {
     fn from(value: postgres::Error) -> Self {
         Self::Error(value)
@@ -229,8 +1118,107 @@ pub fn build_module() -> FFIModule {
 
     module
         .register_fn("client/connect", PostgresClient::connect)
+        .register_fn("client/connect-tls", PostgresClient::connect_tls)
         .register_fn("query", PostgresClient::query)
         .register_fn("batch-execute", PostgresClient::batch_execute)
-        .register_fn("execute", PostgresClient::execute);
+        .register_fn("execute", PostgresClient::execute)
+        .register_fn("cursor/open", PostgresClient::cursor_open)
+        .register_fn("cursor/next-batch", PostgresCursor::next_batch)
+        .register_fn("cursor/close", PostgresCursor::close)
+        .register_fn("error/sqlstate", PostgresError::sqlstate)
+        .register_fn("error/is-unique-violation", PostgresError::is_unique_violation)
+        .register_fn(
+            "error/is-foreign-key-violation",
+            PostgresError::is_foreign_key_violation,
+        )
+        .register_fn("error/message", PostgresError::message)
+        .register_fn("query/assoc", PostgresClient::query_assoc)
+        .register_fn("client/prepare", PostgresClient::prepare)
+        .register_fn("execute-prepared", PostgresClient::execute_prepared)
+        .register_fn("query-prepared", PostgresClient::query_prepared)
+        .register_fn("txn/begin", PostgresClient::txn_begin)
+        .register_fn("txn/execute", PostgresTransaction::execute)
+        .register_fn("txn/query", PostgresTransaction::query)
+        .register_fn("txn/commit", PostgresTransaction::commit)
+        .register_fn("txn/rollback", PostgresTransaction::rollback)
+        .register_fn("pool/connect", PostgresPool::connect)
+        .register_fn("pool/get", PostgresPool::get)
+        .register_fn("pool-client/execute", PostgresPooledClient::execute)
+        .register_fn("pool-client/query", PostgresPooledClient::query);
     module
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sslmode_defaults_to_prefer() {
+        assert_eq!(parse_sslmode("host=localhost dbname=test"), SslMode::Prefer);
+    }
+
+    #[test]
+    fn parse_sslmode_reads_disable() {
+        assert_eq!(
+            parse_sslmode("host=localhost sslmode=disable"),
+            SslMode::Disable
+        );
+    }
+
+    #[test]
+    fn parse_sslmode_reads_require_from_a_uri() {
+        assert_eq!(
+            parse_sslmode("postgres://user@host/db?sslmode=require"),
+            SslMode::Require
+        );
+    }
+
+    #[test]
+    fn parse_sslmode_unknown_value_falls_back_to_prefer() {
+        assert_eq!(parse_sslmode("sslmode=verify-full"), SslMode::Prefer);
+    }
+
+    #[test]
+    fn json_to_ffi_converts_scalars() {
+        assert!(matches!(json_to_ffi(serde_json::Value::Null), FFIValue::Void));
+        assert!(matches!(
+            json_to_ffi(serde_json::json!(true)),
+            FFIValue::BoolV(true)
+        ));
+        assert!(matches!(
+            json_to_ffi(serde_json::json!("hi")),
+            FFIValue::StringV(_)
+        ));
+    }
+
+    #[test]
+    fn json_to_ffi_converts_array_to_vector() {
+        let FFIValue::Vector(items) = json_to_ffi(serde_json::json!([1, 2, 3])) else {
+            panic!("expected a vector");
+        };
+
+        assert_eq!(items.len(), 3);
+    }
+
+    #[test]
+    fn json_to_ffi_converts_object_to_hashmap() {
+        let FFIValue::HashMap(map) = json_to_ffi(serde_json::json!({ "a": 1 })) else {
+            panic!("expected a hash-map");
+        };
+
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn convert_bindings_reports_an_unsupported_argument_instead_of_panicking() {
+        // Steel doesn't have a SQL-bindable representation for a nested
+        // list, so this should surface as an error rather than panic (the
+        // regression `f383eda` replaced a `todo!()` with).
+        let bindings = FFIArg::Vector(RVec::from(vec![FFIArg::Vector(RVec::new())]));
+
+        match convert_bindings(bindings) {
+            Err(PostgresError::UnsupportedType(_)) => {}
+            other => panic!("expected Err(UnsupportedType), got {:?}", other),
+        }
+    }
+}